@@ -1,42 +1,263 @@
 use std::cmp::{max, min};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
 use arboard::Clipboard;
-use image::{GenericImage, GenericImageView, ImageBuffer, Rgba};
+use image::{GenericImage, GenericImageView, ImageBuffer, Pixel, Rgba};
+use pixels::wgpu::{self, util::DeviceExt};
 use pixels::{Pixels, SurfaceTexture};
 use winit::application::ApplicationHandler;
 use winit::dpi::{LogicalSize, PhysicalPosition};
-use winit::event::WindowEvent;
+use winit::event::{ElementState, KeyEvent, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
+use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::{Window, WindowId};
 use xcap::Monitor;
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SelectionMode {
+    Lasso,
+    Rectangle,
+}
+
+impl SelectionMode {
+    fn toggled(self) -> SelectionMode {
+        match self {
+            SelectionMode::Lasso => SelectionMode::Rectangle,
+            SelectionMode::Rectangle => SelectionMode::Lasso,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Mode {
+    Select,
+    Annotate,
+}
+
+impl Mode {
+    fn toggled(self) -> Mode {
+        match self {
+            Mode::Select => Mode::Annotate,
+            Mode::Annotate => Mode::Select,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum AnnotationTool {
+    Rectangle,
+    Arrow,
+    Freehand,
+    Highlight,
+}
+
+#[derive(Clone, Debug)]
+struct Annotation {
+    tool: AnnotationTool,
+    color: Rgba<u8>,
+    thickness: u32,
+    points: Vec<PhysicalPosition<f64>>,
+}
+
+const ANNOTATION_THICKNESS: u32 = 4;
+const ANNOTATION_PALETTE: [Rgba<u8>; 4] = [
+    Rgba([226, 42, 42, 255]),  // red
+    Rgba([247, 181, 0, 255]),  // amber
+    Rgba([38, 166, 91, 255]),  // green
+    Rgba([41, 98, 255, 255]),  // blue
+];
+
+const EDGE_SNAP_RADIUS: i64 = 8;
+const EDGE_SNAP_THRESHOLD: f32 = 60.0;
+
+const HANDLE_RADIUS: f64 = 6.0;
+const HANDLE_COLOR: Rgba<u8> = Rgba([255, 255, 255, 220]);
+const HANDLE_HOVER_COLOR: Rgba<u8> = Rgba([255, 210, 0, 255]);
+
+struct VertexHitbox {
+    index: usize,
+    pos: PhysicalPosition<f64>,
+}
+
 struct App {
     window: Option<Window>,
     image: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    edge_magnitude: Vec<f32>,
     cursor_pos: PhysicalPosition<f64>,
     selecting: bool,
     last_selection_event: Instant,
     selection: Vec<PhysicalPosition<f64>>,
+    selection_mode: SelectionMode,
     pixels: Option<Pixels>,
+    gpu: Option<GpuCompositor>,
+    mode: Mode,
+    annotations: Vec<Annotation>,
+    draft_annotation: Option<Annotation>,
+    active_tool: AnnotationTool,
+    active_color: Rgba<u8>,
+    annotations_dirty: bool,
+    editing: bool,
+    hitboxes: Vec<VertexHitbox>,
+    drag_vertex: Option<usize>,
+    hovered_vertex: Option<usize>,
+    output_path: Option<PathBuf>,
+    also_copy: bool,
 }
 
 impl App {
-    fn new(image: ImageBuffer<Rgba<u8>, Vec<u8>>) -> App {
+    fn new(image: ImageBuffer<Rgba<u8>, Vec<u8>>, output_path: Option<PathBuf>, also_copy: bool) -> App {
+        let edge_magnitude = compute_edge_magnitude(&image);
         App {
             window: None,
             image,
+            edge_magnitude,
             cursor_pos: PhysicalPosition { x: 0.0, y: 0.0 },
             selecting: false,
             last_selection_event: Instant::now(),
             selection: vec![],
+            selection_mode: SelectionMode::Lasso,
             pixels: None,
+            gpu: None,
+            mode: Mode::Select,
+            annotations: vec![],
+            draft_annotation: None,
+            active_tool: AnnotationTool::Rectangle,
+            active_color: ANNOTATION_PALETTE[0],
+            annotations_dirty: false,
+            editing: false,
+            hitboxes: vec![],
+            drag_vertex: None,
+            hovered_vertex: None,
+            output_path,
+            also_copy,
+        }
+    }
+
+    fn finish_capture(&self) {
+        let image = self.selection_image();
+        match &self.output_path {
+            Some(path) => {
+                save_image_to_file(&image, path);
+                if self.also_copy {
+                    provide_image_for_pasting(&image);
+                }
+            }
+            None => provide_image_for_pasting(&image),
+        }
+    }
+
+    fn has_selection(&self) -> bool {
+        match self.selection_mode {
+            SelectionMode::Lasso => self.selection.len() >= 3,
+            SelectionMode::Rectangle => self.selection.len() >= 2,
+        }
+    }
+
+    fn layout_hitboxes(&mut self) {
+        self.hitboxes = self
+            .selection
+            .iter()
+            .enumerate()
+            .map(|(index, &pos)| VertexHitbox { index, pos })
+            .collect();
+    }
+
+    fn hit_test_vertex(&self, pos: PhysicalPosition<f64>) -> Option<usize> {
+        self.hitboxes
+            .iter()
+            .map(|h| {
+                let dx = h.pos.x - pos.x;
+                let dy = h.pos.y - pos.y;
+                (h.index, dx * dx + dy * dy)
+            })
+            .filter(|&(_, dist2)| dist2 <= HANDLE_RADIUS * HANDLE_RADIUS)
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+            .map(|(index, _)| index)
+    }
+
+    fn display_image(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut image = self.annotated_image();
+        if self.editing {
+            for hitbox in &self.hitboxes {
+                let color = if self.hovered_vertex == Some(hitbox.index) {
+                    HANDLE_HOVER_COLOR
+                } else {
+                    HANDLE_COLOR
+                };
+                draw_handle(&mut image, hitbox.pos, color);
+            }
+        }
+        image
+    }
+
+    fn set_tool(&mut self, tool: AnnotationTool) {
+        self.active_tool = tool;
+        println!("Annotation tool: {:?}", tool);
+    }
+
+    fn cycle_color(&mut self) {
+        let index = ANNOTATION_PALETTE
+            .iter()
+            .position(|&c| c == self.active_color)
+            .unwrap_or(0);
+        self.active_color = ANNOTATION_PALETTE[(index + 1) % ANNOTATION_PALETTE.len()];
+        println!("Annotation color: {:?}", self.active_color);
+    }
+
+    fn annotated_image(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let mut image = self.image.clone();
+        for annotation in &self.annotations {
+            draw_annotation(&mut image, annotation);
+        }
+        if let Some(draft) = &self.draft_annotation {
+            draw_annotation(&mut image, draft);
+        }
+        image
+    }
+
+    fn snap_to_edge(&self, pos: PhysicalPosition<f64>) -> PhysicalPosition<f64> {
+        let iw = self.image.width() as i64;
+        let ih = self.image.height() as i64;
+        let cx = pos.x.round() as i64;
+        let cy = pos.y.round() as i64;
+
+        let mut best_xy: Option<(i64, i64)> = None;
+        let mut best_mag = EDGE_SNAP_THRESHOLD;
+        let mut best_dist2 = i64::MAX;
+
+        for dy in -EDGE_SNAP_RADIUS..=EDGE_SNAP_RADIUS {
+            for dx in -EDGE_SNAP_RADIUS..=EDGE_SNAP_RADIUS {
+                let x = cx + dx;
+                let y = cy + dy;
+                if x < 0 || y < 0 || x >= iw || y >= ih {
+                    continue;
+                }
+                let mag = self.edge_magnitude[(y as usize) * (iw as usize) + x as usize];
+                if mag < best_mag {
+                    continue;
+                }
+                let dist2 = dx * dx + dy * dy;
+                if mag > best_mag || dist2 < best_dist2 {
+                    best_mag = mag;
+                    best_dist2 = dist2;
+                    best_xy = Some((x, y));
+                }
+            }
+        }
+
+        match best_xy {
+            Some((x, y)) => PhysicalPosition::new(x as f64, y as f64),
+            // No edge strong enough nearby: fall back to the raw cursor path.
+            None => pos,
         }
     }
 
     fn selection_image(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
-        let iw = self.image.width();
-        let ih = self.image.height();
+        // Crop from the annotated image so markup goes out with the screenshot.
+        let source = self.annotated_image();
+        let iw = source.width();
+        let ih = source.height();
         let mut min_x: u32 = iw;
         let mut min_y: u32 = ih;
         let mut max_x: u32 = 0;
@@ -50,14 +271,30 @@ impl App {
         println!("Selection size is {} x {}", max_x - min_x, max_y - min_y);
         let mut image: ImageBuffer<Rgba<u8>, Vec<u8>> =
             ImageBuffer::new(max_x - min_x, max_y - min_y);
-        let inside_mask = selection_mask(iw as usize, ih as usize, &self.selection);
-        for y in min_y..max_y {
-            for x in min_x..max_x {
-                unsafe {
-                    let pixel = self.image.unsafe_get_pixel(x, y);
-                    let i = (y * iw) + x;
-                    if inside_mask[i as usize] {
-                        image.unsafe_put_pixel(x - min_x, y - min_y, pixel);
+        match self.selection_mode {
+            // Rectangle selections are already axis-aligned, so every pixel
+            // in the bounding box belongs to the crop: no mask needed.
+            SelectionMode::Rectangle => {
+                for y in min_y..max_y {
+                    for x in min_x..max_x {
+                        unsafe {
+                            let pixel = source.unsafe_get_pixel(x, y);
+                            image.unsafe_put_pixel(x - min_x, y - min_y, pixel);
+                        }
+                    }
+                }
+            }
+            SelectionMode::Lasso => {
+                let inside_mask = selection_mask(iw as usize, ih as usize, &self.selection);
+                for y in min_y..max_y {
+                    for x in min_x..max_x {
+                        unsafe {
+                            let pixel = source.unsafe_get_pixel(x, y);
+                            let i = (y * iw) + x;
+                            if inside_mask[i as usize] {
+                                image.unsafe_put_pixel(x - min_x, y - min_y, pixel);
+                            }
+                        }
                     }
                 }
             }
@@ -66,6 +303,264 @@ impl App {
     }
 }
 
+// Full-screen-triangle pipeline that dims outside the selection mask, instead of
+// rewriting the whole `pixels` frame buffer on the CPU every mouse move.
+const COMPOSITE_SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let x = f32((vertex_index << 1u) & 2u);
+    let y = f32(vertex_index & 2u);
+    out.position = vec4<f32>(x * 2.0 - 1.0, 1.0 - y * 2.0, 0.0, 1.0);
+    out.uv = vec2<f32>(x, y);
+    return out;
+}
+
+@group(0) @binding(0) var screenshot_tex: texture_2d<f32>;
+@group(0) @binding(1) var mask_tex: texture_2d<f32>;
+@group(0) @binding(2) var tex_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(screenshot_tex, tex_sampler, in.uv);
+    let inside = textureSample(mask_tex, tex_sampler, in.uv).r;
+    let dim = mix(0.5, 1.0, inside);
+    return vec4<f32>(color.rgb * dim, color.a);
+}
+"#;
+
+struct GpuCompositor {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    screenshot_texture: wgpu::Texture,
+    mask_texture: wgpu::Texture,
+    width: u32,
+    height: u32,
+}
+
+impl GpuCompositor {
+    fn new(pixels: &Pixels, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> GpuCompositor {
+        let device = pixels.device();
+        let queue = pixels.queue();
+        let width = image.width();
+        let height = image.height();
+        let extent = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let screenshot_texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("freeshot-screenshot"),
+                size: extent,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            image.as_raw(),
+        );
+        let screenshot_view =
+            screenshot_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mask_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("freeshot-selection-mask"),
+            size: extent,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let mask_view = mask_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("freeshot-sampler"),
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("freeshot-composite-shader"),
+            source: wgpu::ShaderSource::Wgsl(COMPOSITE_SHADER.into()),
+        });
+
+        let bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("freeshot-bind-group-layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("freeshot-bind-group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&screenshot_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&mask_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("freeshot-pipeline-layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("freeshot-composite-pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: pixels.render_texture_format(),
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        GpuCompositor {
+            pipeline,
+            bind_group,
+            screenshot_texture,
+            mask_texture,
+            width,
+            height,
+        }
+    }
+
+    fn update_screenshot(&self, queue: &wgpu::Queue, image: &ImageBuffer<Rgba<u8>, Vec<u8>>) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.screenshot_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            image.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * self.width),
+                rows_per_image: Some(self.height),
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    fn update_mask(&self, queue: &wgpu::Queue, mask: &[bool]) {
+        // 1 byte per pixel: 0 = outside selection, 255 = inside.
+        let bytes: Vec<u8> = mask
+            .iter()
+            .map(|&inside| if inside { 255 } else { 0 })
+            .collect();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.mask_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &bytes,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width),
+                rows_per_image: Some(self.height),
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    fn render(&self, encoder: &mut wgpu::CommandEncoder, render_target: &wgpu::TextureView) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("freeshot-composite-pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: render_target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         let height = self.image.height() * 10 / 11;
@@ -92,64 +587,194 @@ impl ApplicationHandler for App {
                     let pixels = Pixels::new(iw, ih, surface_texture).unwrap();
                     self.pixels = Some(pixels);
                 }
-                let pixels = self.pixels.as_mut().unwrap();
-                let frame = pixels.frame_mut();
-                // TODO the following can probably be done faster somehow?!
-                if self.selection.len() >= 3 {
-                    let inside_mask = selection_mask(iw as usize, ih as usize, &self.selection);
-                    for (i, rgba) in self.image.pixels().enumerate() {
-                        let color: &Rgba<u8> = rgba;
-                        frame[i * 4 + 0] = color[0];
-                        frame[i * 4 + 1] = color[1];
-                        frame[i * 4 + 2] = color[2];
-                        // reduce alpha if outside of selection
-                        if inside_mask[i] {
-                            frame[i * 4 + 3] = color[3];
-                        } else {
-                            frame[i * 4 + 3] = color[3] / 2;
+                if self.gpu.is_none() {
+                    let pixels = self.pixels.as_ref().unwrap();
+                    self.gpu = Some(GpuCompositor::new(pixels, &self.image));
+                }
+                self.layout_hitboxes();
+                let has_selection = self.has_selection();
+                let inside_mask = if has_selection {
+                    match self.selection_mode {
+                        SelectionMode::Lasso => {
+                            selection_mask(iw as usize, ih as usize, &self.selection)
+                        }
+                        SelectionMode::Rectangle => {
+                            rectangle_mask(iw as usize, ih as usize, &self.selection)
                         }
                     }
                 } else {
-                    for (i, rgba) in self.image.pixels().enumerate() {
-                        let color: &Rgba<u8> = rgba;
-                        frame[i * 4 + 0] = color[0];
-                        frame[i * 4 + 1] = color[1];
-                        frame[i * 4 + 2] = color[2];
-                        frame[i * 4 + 3] = color[3];
-                    }
+                    vec![true; (iw * ih) as usize]
+                };
+
+                let pixels = self.pixels.as_mut().unwrap();
+                let gpu = self.gpu.as_ref().unwrap();
+                // The screenshot itself is only re-uploaded when annotations changed
+                // or while editing (the vertex handles are drawn into it); every other
+                // redraw just uploads the 1-byte-per-pixel selection mask and lets the
+                // fragment shader do the dimming, instead of rewriting the RGBA frame
+                // on the CPU.
+                if self.annotations_dirty || self.editing {
+                    gpu.update_screenshot(pixels.queue(), &self.display_image());
+                    self.annotations_dirty = false;
                 }
-                pixels.render().expect("rendered");
+                gpu.update_mask(pixels.queue(), &inside_mask);
+                pixels
+                    .render_with(|encoder, render_target, _context| {
+                        gpu.render(encoder, render_target);
+                        Ok(())
+                    })
+                    .expect("rendered");
             }
             WindowEvent::CursorMoved {
                 device_id: _,
                 position,
             } => {
                 self.cursor_pos = position;
+
+                if self.editing {
+                    // Dragging a vertex and hover feedback both need to track the
+                    // cursor exactly, unlike the throttled sampling below.
+                    if let Some(i) = self.drag_vertex {
+                        if i < self.selection.len() {
+                            self.selection[i] = position;
+                            self.window.as_ref().unwrap().request_redraw();
+                        }
+                    } else {
+                        let hovered = self.hit_test_vertex(position);
+                        if hovered != self.hovered_vertex {
+                            self.hovered_vertex = hovered;
+                            self.window.as_ref().unwrap().request_redraw();
+                        }
+                    }
+                }
+
                 let now = Instant::now();
                 let elapsed = now - self.last_selection_event;
-                if self.selecting && elapsed.as_millis() >= 100 {
-                    self.selection.push(position);
-                    // redraw to make the selection visible correctly
-                    self.window.as_ref().unwrap().request_redraw();
-                    self.last_selection_event = now;
+                if elapsed.as_millis() >= 100 {
+                    let mut redraw = false;
+                    if self.selecting {
+                        match self.selection_mode {
+                            SelectionMode::Lasso => {
+                                self.selection.push(self.snap_to_edge(position));
+                            }
+                            SelectionMode::Rectangle => {
+                                // Only ever the press point and the current drag point.
+                                if self.selection.len() < 2 {
+                                    self.selection.push(position);
+                                } else {
+                                    self.selection[1] = position;
+                                }
+                            }
+                        }
+                        redraw = true;
+                    }
+                    if let Some(annotation) = self.draft_annotation.as_mut() {
+                        match annotation.tool {
+                            // Freehand accumulates every point; the other tools only
+                            // ever keep the press point and the current drag point.
+                            AnnotationTool::Freehand => annotation.points.push(position),
+                            _ => {
+                                if annotation.points.len() < 2 {
+                                    annotation.points.push(position);
+                                } else {
+                                    annotation.points[1] = position;
+                                }
+                            }
+                        }
+                        self.annotations_dirty = true;
+                        redraw = true;
+                    }
+                    if redraw {
+                        // redraw to make the selection/annotation visible correctly
+                        self.window.as_ref().unwrap().request_redraw();
+                        self.last_selection_event = now;
+                    }
                 }
             }
             WindowEvent::MouseInput {
                 device_id: _,
                 state,
                 button: _,
-            } => {
-                if state.is_pressed() {
-                    self.selecting = true;
-                    self.selection = vec![];
-                } else {
-                    println!("Mouse up, finishing");
+            } => match self.mode {
+                Mode::Select if self.editing => {
+                    if state.is_pressed() {
+                        self.drag_vertex = self.hit_test_vertex(self.cursor_pos);
+                    } else {
+                        self.drag_vertex = None;
+                    }
+                }
+                Mode::Select => {
+                    if state.is_pressed() {
+                        self.selecting = true;
+                        self.selection = match self.selection_mode {
+                            SelectionMode::Lasso => vec![],
+                            SelectionMode::Rectangle => vec![self.cursor_pos],
+                        };
+                    } else {
+                        println!("Mouse up, entering edit mode (drag vertices, Enter to finish)");
+                        self.selecting = false;
+                        self.editing = true;
+                    }
+                }
+                Mode::Annotate => {
+                    if state.is_pressed() {
+                        self.draft_annotation = Some(Annotation {
+                            tool: self.active_tool,
+                            color: self.active_color,
+                            thickness: ANNOTATION_THICKNESS,
+                            points: vec![self.cursor_pos],
+                        });
+                    } else if let Some(annotation) = self.draft_annotation.take() {
+                        self.annotations.push(annotation);
+                        self.annotations_dirty = true;
+                        self.window.as_ref().unwrap().request_redraw();
+                    }
+                }
+            },
+            WindowEvent::KeyboardInput {
+                device_id: _,
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(code),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                is_synthetic: _,
+            } => match code {
+                KeyCode::KeyR => {
+                    self.selection_mode = self.selection_mode.toggled();
                     self.selecting = false;
-                    provide_image_for_pasting(&self.selection_image());
+                    self.selection = vec![];
+                    self.editing = false;
+                    self.hitboxes = vec![];
+                    self.drag_vertex = None;
+                    self.hovered_vertex = None;
+                    self.annotations_dirty = true;
+                    println!("Selection mode: {:?}", self.selection_mode);
+                }
+                KeyCode::Tab => {
+                    self.mode = self.mode.toggled();
+                    println!("Mode: {:?}", self.mode);
+                }
+                KeyCode::Digit1 => self.set_tool(AnnotationTool::Rectangle),
+                KeyCode::Digit2 => self.set_tool(AnnotationTool::Arrow),
+                KeyCode::Digit3 => self.set_tool(AnnotationTool::Freehand),
+                KeyCode::Digit4 => self.set_tool(AnnotationTool::Highlight),
+                KeyCode::KeyC => self.cycle_color(),
+                KeyCode::Enter if self.editing && self.has_selection() => {
+                    self.finish_capture();
+                    self.editing = false;
+                    self.selection = vec![];
+                    self.hitboxes = vec![];
+                    self.drag_vertex = None;
+                    self.hovered_vertex = None;
+                    self.annotations_dirty = true;
                     // keep process alive for pasting!
                     //event_loop.exit();
                 }
-            }
+                _ => {}
+            },
             _ => (),
         }
     }
@@ -205,6 +830,208 @@ fn selection_mask(width: usize, height: usize, polygon: &Vec<PhysicalPosition<f6
     mask
 }
 
+fn compute_edge_magnitude(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> Vec<f32> {
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+
+    let mut gray = vec![0f32; width * height];
+    for (x, y, pixel) in image.enumerate_pixels() {
+        gray[y as usize * width + x as usize] = pixel.to_luma().0[0] as f32;
+    }
+
+    let mut mag = vec![0f32; width * height];
+    if width < 3 || height < 3 {
+        return mag;
+    }
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let tl = gray[(y - 1) * width + (x - 1)];
+            let t = gray[(y - 1) * width + x];
+            let tr = gray[(y - 1) * width + (x + 1)];
+            let l = gray[y * width + (x - 1)];
+            let r = gray[y * width + (x + 1)];
+            let bl = gray[(y + 1) * width + (x - 1)];
+            let b = gray[(y + 1) * width + x];
+            let br = gray[(y + 1) * width + (x + 1)];
+
+            let gx = (tr + 2.0 * r + br) - (tl + 2.0 * l + bl);
+            let gy = (bl + 2.0 * b + br) - (tl + 2.0 * t + tr);
+            mag[y * width + x] = (gx * gx + gy * gy).sqrt();
+        }
+    }
+    mag
+}
+
+fn rectangle_mask(width: usize, height: usize, corners: &Vec<PhysicalPosition<f64>>) -> Vec<bool> {
+    let mut mask = vec![false; width * height];
+    if corners.len() < 2 {
+        return mask;
+    }
+
+    let min_x = corners[0].x.min(corners[1].x).max(0.0) as usize;
+    let max_x = corners[0]
+        .x
+        .max(corners[1].x)
+        .min((width - 1) as f64) as usize;
+    let min_y = corners[0].y.min(corners[1].y).max(0.0) as usize;
+    let max_y = corners[0]
+        .y
+        .max(corners[1].y)
+        .min((height - 1) as f64) as usize;
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            mask[y * width + x] = true;
+        }
+    }
+    mask
+}
+
+fn draw_annotation(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, annotation: &Annotation) {
+    if annotation.points.is_empty() {
+        return;
+    }
+    let start = annotation.points[0];
+    let end = *annotation.points.last().unwrap();
+    match annotation.tool {
+        AnnotationTool::Freehand => {
+            for pair in annotation.points.windows(2) {
+                draw_line(image, pair[0], pair[1], annotation.color, annotation.thickness);
+            }
+        }
+        AnnotationTool::Rectangle => {
+            draw_rect_outline(image, start, end, annotation.color, annotation.thickness)
+        }
+        AnnotationTool::Highlight => {
+            let mut fill_color = annotation.color;
+            fill_color[3] = 90;
+            fill_rect(image, start, end, fill_color);
+        }
+        AnnotationTool::Arrow => draw_arrow(image, start, end, annotation.color, annotation.thickness),
+    }
+}
+
+fn blend_pixel(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, x: i64, y: i64, color: Rgba<u8>) {
+    if x < 0 || y < 0 || x as u32 >= image.width() || y as u32 >= image.height() {
+        return;
+    }
+    let (x, y) = (x as u32, y as u32);
+    let base = image.get_pixel(x, y);
+    let alpha = color[3] as f32 / 255.0;
+    let blended = Rgba([
+        (color[0] as f32 * alpha + base[0] as f32 * (1.0 - alpha)) as u8,
+        (color[1] as f32 * alpha + base[1] as f32 * (1.0 - alpha)) as u8,
+        (color[2] as f32 * alpha + base[2] as f32 * (1.0 - alpha)) as u8,
+        255,
+    ]);
+    image.put_pixel(x, y, blended);
+}
+
+fn draw_line(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    p0: PhysicalPosition<f64>,
+    p1: PhysicalPosition<f64>,
+    color: Rgba<u8>,
+    thickness: u32,
+) {
+    let (mut x0, mut y0) = (p0.x as i64, p0.y as i64);
+    let (x1, y1) = (p1.x as i64, p1.y as i64);
+    let dx = (x1 - x0).abs();
+    let dy = -(y1 - y0).abs();
+    let sx = if x0 < x1 { 1 } else { -1 };
+    let sy = if y0 < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    let half_thickness = (thickness as i64 / 2).max(1);
+
+    loop {
+        for oy in -half_thickness..=half_thickness {
+            for ox in -half_thickness..=half_thickness {
+                blend_pixel(image, x0 + ox, y0 + oy, color);
+            }
+        }
+        if x0 == x1 && y0 == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x0 += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y0 += sy;
+        }
+    }
+}
+
+fn draw_rect_outline(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    start: PhysicalPosition<f64>,
+    end: PhysicalPosition<f64>,
+    color: Rgba<u8>,
+    thickness: u32,
+) {
+    let top_left = PhysicalPosition::new(start.x.min(end.x), start.y.min(end.y));
+    let top_right = PhysicalPosition::new(start.x.max(end.x), start.y.min(end.y));
+    let bottom_left = PhysicalPosition::new(start.x.min(end.x), start.y.max(end.y));
+    let bottom_right = PhysicalPosition::new(start.x.max(end.x), start.y.max(end.y));
+    draw_line(image, top_left, top_right, color, thickness);
+    draw_line(image, top_right, bottom_right, color, thickness);
+    draw_line(image, bottom_right, bottom_left, color, thickness);
+    draw_line(image, bottom_left, top_left, color, thickness);
+}
+
+fn fill_rect(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    start: PhysicalPosition<f64>,
+    end: PhysicalPosition<f64>,
+    color: Rgba<u8>,
+) {
+    let min_x = start.x.min(end.x).max(0.0) as u32;
+    let max_x = start.x.max(end.x).min(image.width() as f64 - 1.0) as u32;
+    let min_y = start.y.min(end.y).max(0.0) as u32;
+    let max_y = start.y.max(end.y).min(image.height() as f64 - 1.0) as u32;
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            blend_pixel(image, x as i64, y as i64, color);
+        }
+    }
+}
+
+fn draw_handle(image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, pos: PhysicalPosition<f64>, color: Rgba<u8>) {
+    let radius = HANDLE_RADIUS as i64;
+    let (cx, cy) = (pos.x as i64, pos.y as i64);
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            if dx * dx + dy * dy <= radius * radius {
+                blend_pixel(image, cx + dx, cy + dy, color);
+            }
+        }
+    }
+}
+
+fn draw_arrow(
+    image: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    start: PhysicalPosition<f64>,
+    end: PhysicalPosition<f64>,
+    color: Rgba<u8>,
+    thickness: u32,
+) {
+    draw_line(image, start, end, color, thickness);
+
+    let angle = (end.y - start.y).atan2(end.x - start.x);
+    let head_len = 18.0;
+    let head_angle = std::f64::consts::FRAC_PI_6;
+    for sign in [-1.0, 1.0] {
+        let wing_angle = angle + std::f64::consts::PI - sign * head_angle;
+        let wing = PhysicalPosition::new(
+            end.x + head_len * wing_angle.cos(),
+            end.y + head_len * wing_angle.sin(),
+        );
+        draw_line(image, end, wing, color, thickness);
+    }
+}
+
 fn provide_image_for_pasting(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) {
     let mut clipboard = Clipboard::new().unwrap();
     let raw_rgba = image.clone().into_raw();
@@ -218,12 +1045,74 @@ fn provide_image_for_pasting(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) {
     // TODO keep process alive?!
 }
 
+fn save_image_to_file(image: &ImageBuffer<Rgba<u8>, Vec<u8>>, path: &Path) {
+    // Format (PNG/JPEG/...) is picked by the `image` crate from the file extension.
+    match image.save(path) {
+        Ok(()) => println!("Image saved to {}", path.display()),
+        Err(err) => eprintln!("Failed to save image to {}: {err}", path.display()),
+    }
+}
+
+fn capture_virtual_desktop(monitors: &[Monitor]) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    assert!(!monitors.is_empty(), "no monitors to capture");
+
+    let mut min_x = i32::MAX;
+    let mut min_y = i32::MAX;
+    let mut max_x = i32::MIN;
+    let mut max_y = i32::MIN;
+    let mut captures = Vec::with_capacity(monitors.len());
+
+    for monitor in monitors {
+        let x = monitor.x().unwrap();
+        let y = monitor.y().unwrap();
+        let image = monitor.capture_image().unwrap();
+        min_x = min(min_x, x);
+        min_y = min(min_y, y);
+        max_x = max(max_x, x + image.width() as i32);
+        max_y = max(max_y, y + image.height() as i32);
+        captures.push((x, y, image));
+    }
+
+    let mut canvas = ImageBuffer::new((max_x - min_x) as u32, (max_y - min_y) as u32);
+    for (x, y, image) in captures {
+        canvas
+            .copy_from(&image, (x - min_x) as u32, (y - min_y) as u32)
+            .expect("monitor image fits the virtual desktop canvas");
+    }
+    canvas
+}
+
+struct CliArgs {
+    output_path: Option<PathBuf>,
+    also_copy: bool,
+}
+
+fn parse_cli_args() -> CliArgs {
+    let mut output_path = None;
+    let mut also_copy = false;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                output_path = args.next().map(PathBuf::from);
+            }
+            "--copy" => also_copy = true,
+            other => eprintln!("Ignoring unknown argument: {other}"),
+        }
+    }
+    CliArgs {
+        output_path,
+        also_copy,
+    }
+}
+
 fn main() {
+    let cli_args = parse_cli_args();
+
     // Capture screenshot
     let monitors = Monitor::all().unwrap();
-    let monitor = &monitors[0]; // Display the first monitor for simplicity
-    let image = monitor.capture_image().unwrap();
-    let mut app = App::new(image);
+    let image = capture_virtual_desktop(&monitors);
+    let mut app = App::new(image, cli_args.output_path, cli_args.also_copy);
 
     // Setup window
     let event_loop = EventLoop::new().unwrap();